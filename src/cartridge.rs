@@ -1,23 +1,48 @@
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct NesRom {
     pub prg_rom: Vec<u8>, // Program-ROM
-    pub chr_rom: Vec<u8>, // Character ROM ( Sprites)
-    //pub s_ram: Vec<u8>,   // Save RAM
-    pub mapper: u8, // mapper type
+    pub chr_rom: Vec<u8>, // Character ROM ( Sprites), or backing store for CHR-RAM
+    pub chr_ram: bool, // true when `chr_rom` is writable CHR-RAM, not CHR-ROM
+    pub prg_ram: Vec<u8>, // PRG-RAM backing $6000-$7FFF
+    pub battery: bool, // true when `prg_ram` is battery-backed and should be persisted
+    pub mapper: u16, // mapper type
+    pub submapper: u8, // NES 2.0 submapper number (0 for iNES 1.0)
     pub mirror: Mirroring, // mirroring mode type
-                    //pub battery: u8,      //battery present
+
+    // NES 2.0 only (all zero/default when `is_nes2` is false)
+    pub is_nes2: bool,
+    pub prg_ram_size: usize,    // volatile PRG-RAM at $6000-$7FFF, in bytes
+    pub prg_nvram_size: usize,  // battery-backed PRG-RAM, in bytes
+    pub chr_ram_size: usize,    // volatile CHR-RAM, in bytes
+    pub chr_nvram_size: usize,  // battery-backed CHR-RAM, in bytes
+    pub timing: Timing,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Mirroring {
     Vertical,
     Horizontal,
     FourScreen,
+    // Single-screen modes: all four logical name tables show the same
+    // physical page. Selected at runtime by mappers like MMC1 rather than
+    // fixed at cartridge load.
+    SingleScreenLower,
+    SingleScreenUpper,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timing {
+    Ntsc,
+    Pal,
+    MultiRegion,
+    Dendy,
 }
 
 const NES_TAG: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A];
 const PRG_ROM_PAGE_SIZE: usize = 16384;
 const CHR_ROM_PAGE_SIZE: usize = 8192;
+const CHR_RAM_DEFAULT_SIZE: usize = 8192;
+const PRG_RAM_DEFAULT_SIZE: usize = 8192;
 
 impl NesRom {
     //https://www.nesdev.org/wiki/INES
@@ -26,8 +51,6 @@ impl NesRom {
             return Err("File is not in iNES file format".to_string());
         }
 
-        let mapper = (raw[7] & 0b1111_0000) | (raw[6] >> 4);
-
         // Check flags 7 ( This is bytes 7 of ROM)
         // 76543210
         // ||||||||
@@ -36,9 +59,7 @@ impl NesRom {
         // ||||++--- If equal to 2, flags 8-15 are in NES 2.0 format
         // ++++----- Upper nybble of mapper number
         let ines_ver = (raw[7] >> 2) & 0b11;
-        if ines_ver != 0 {
-            return Err("NES2.0 format is not supported".to_string());
-        }
+        let is_nes2 = ines_ver == 2;
 
         // Flags 6 ( This is bytes 6 of ROM )
         // 76543210
@@ -51,25 +72,113 @@ impl NesRom {
         // ++++----- Lower nybble of mapper number
         let four_screen = raw[6] & 0b1000 != 0;
         let vertical_mirroring = raw[6] & 0b1 != 0;
+        let battery = raw[6] & 0b10 != 0;
         let screen_mirroring = match (four_screen, vertical_mirroring) {
             (true, _) => Mirroring::FourScreen,
             (false, true) => Mirroring::Vertical,
             (false, false) => Mirroring::Horizontal,
         };
 
-        let prg_rom_size = raw[4] as usize * PRG_ROM_PAGE_SIZE;
-        let chr_rom_size = raw[5] as usize * CHR_ROM_PAGE_SIZE;
+        // Mapper number: low nibble from flags 6, high nibble from flags 7, and
+        // (NES 2.0 only) bits 8-11 from the low nibble of byte 8.
+        let mapper_lo = (raw[7] & 0b1111_0000) | (raw[6] >> 4);
+        let mapper = if is_nes2 {
+            ((raw[8] & 0x0F) as u16) << 8 | mapper_lo as u16
+        } else {
+            mapper_lo as u16
+        };
+        let submapper = if is_nes2 { raw[8] >> 4 } else { 0 };
 
         let skip_trainer = raw[6] & 0b100 != 0;
 
+        // PRG/CHR bank counts: NES 2.0 adds four extra bits of bank count in the
+        // low/high nibble of byte 9, stacked above the byte 4/5 counts.
+        let (prg_rom_banks, chr_rom_banks) = if is_nes2 {
+            let prg_hi = (raw[9] & 0x0F) as usize;
+            let chr_hi = (raw[9] >> 4) as usize;
+            ((prg_hi << 8) | raw[4] as usize, (chr_hi << 8) | raw[5] as usize)
+        } else {
+            (raw[4] as usize, raw[5] as usize)
+        };
+
+        let prg_rom_size = prg_rom_banks * PRG_ROM_PAGE_SIZE;
+        let chr_rom_size = chr_rom_banks * CHR_ROM_PAGE_SIZE;
+
         let prg_rom_start = 16 + if skip_trainer { 512 } else { 0 };
         let chr_rom_start = prg_rom_start + prg_rom_size;
 
+        // NES 2.0 only: PRG/CHR (N)VRAM sizes, each nibble a shift count meaning
+        // `64 << shift` bytes, with 0 meaning "not present". Byte 12 also carries
+        // the CPU/PPU timing mode.
+        let (prg_ram_size, prg_nvram_size, chr_ram_size, chr_nvram_size, timing) = if is_nes2 {
+            (
+                decode_nes2_ram_size(raw[10] & 0x0F),
+                decode_nes2_ram_size(raw[10] >> 4),
+                decode_nes2_ram_size(raw[11] & 0x0F),
+                decode_nes2_ram_size(raw[11] >> 4),
+                match raw[12] & 0b11 {
+                    0 => Timing::Ntsc,
+                    1 => Timing::Pal,
+                    2 => Timing::MultiRegion,
+                    _ => Timing::Dendy,
+                },
+            )
+        } else {
+            (0, 0, 0, 0, Timing::Ntsc)
+        };
+
+        // No CHR-ROM banks means the cartridge relies on writable CHR-RAM
+        // instead; fall back to the NES 2.0 size if given, else the common 8KB.
+        let chr_ram = chr_rom_banks == 0;
+        let chr_rom_data = if chr_ram {
+            let ram_size = if chr_ram_size > 0 {
+                chr_ram_size
+            } else {
+                CHR_RAM_DEFAULT_SIZE
+            };
+            vec![0; ram_size]
+        } else {
+            raw[chr_rom_start..(chr_rom_start + chr_rom_size)].to_vec()
+        };
+
+        // NES 2.0 gives an exact PRG-RAM size (volatile and battery-backed banks
+        // sit side by side at $6000-$7FFF); when it gives none, and always for
+        // iNES 1.0 (which doesn't specify one), the common 8KB is allocated.
+        let prg_ram_alloc_size = if is_nes2 {
+            let nes2_size = prg_ram_size + prg_nvram_size;
+            if nes2_size > 0 {
+                nes2_size
+            } else {
+                PRG_RAM_DEFAULT_SIZE
+            }
+        } else {
+            PRG_RAM_DEFAULT_SIZE
+        };
+
         Ok(NesRom {
             prg_rom: raw[prg_rom_start..(prg_rom_start + prg_rom_size)].to_vec(),
-            chr_rom: raw[chr_rom_start..(chr_rom_start + chr_rom_size)].to_vec(),
-            mapper: mapper,
+            chr_rom: chr_rom_data,
+            chr_ram,
+            prg_ram: vec![0; prg_ram_alloc_size],
+            battery,
+            mapper,
+            submapper,
             mirror: screen_mirroring,
+            is_nes2,
+            prg_ram_size,
+            prg_nvram_size,
+            chr_ram_size,
+            chr_nvram_size,
+            timing,
         })
     }
 }
+
+// NES 2.0 (N)VRAM size nibble: 0 means absent, otherwise `64 << shift` bytes.
+fn decode_nes2_ram_size(shift: u8) -> usize {
+    if shift == 0 {
+        0
+    } else {
+        64usize << shift
+    }
+}
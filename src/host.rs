@@ -0,0 +1,28 @@
+use bitflags::bitflags;
+
+// The boundary between the emulator core and a concrete frontend (SDL2,
+// browser canvas/WebAudio, microcontroller peripherals). The core drives a
+// frame loop and pushes/pulls through this trait instead of touching a
+// window, file system, or audio backend directly. The crate itself is not
+// no_std: `Emulator` still uses `std::fs` to load a ROM from a path and to
+// read and write `.sav` files.
+pub trait HostPlatform {
+    fn render(&mut self, frame: &[u8]);
+    fn poll_input(&mut self) -> Buttons;
+    fn queue_audio(&mut self, samples: &[f32]);
+}
+
+bitflags! {
+    // Standard NES controller button order, matching the $4016/$4017 shift
+    // register read order (A first, Right last).
+    pub struct Buttons: u8 {
+        const A      = 0b0000_0001;
+        const B      = 0b0000_0010;
+        const SELECT = 0b0000_0100;
+        const START  = 0b0000_1000;
+        const UP     = 0b0001_0000;
+        const DOWN   = 0b0010_0000;
+        const LEFT   = 0b0100_0000;
+        const RIGHT  = 0b1000_0000;
+    }
+}
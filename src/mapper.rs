@@ -0,0 +1,432 @@
+use crate::cartridge::{Mirroring, NesRom};
+
+//https://www.nesdev.org/wiki/Mapper
+pub trait Mapper {
+    fn cpu_read(&mut self, addr: u16) -> u8;
+    fn cpu_write(&mut self, addr: u16, val: u8);
+    fn ppu_read(&mut self, addr: u16) -> u8;
+    fn ppu_write(&mut self, addr: u16, val: u8);
+    fn mirroring(&self) -> Mirroring;
+
+    // PRG-RAM at $6000-$7FFF, for battery save persistence. Mappers without
+    // PRG-RAM keep the default no-op/empty implementations.
+    fn prg_ram(&self) -> &[u8] {
+        &[]
+    }
+    fn load_prg_ram(&mut self, _data: &[u8]) {}
+}
+
+// Copies a loaded save file into `prg_ram`, ignoring any trailing bytes and
+// leaving bytes the save doesn't cover untouched, in case the save on disk
+// doesn't match the cartridge's current PRG-RAM size.
+fn load_prg_ram_into(prg_ram: &mut [u8], data: &[u8]) {
+    let len = prg_ram.len().min(data.len());
+    prg_ram[..len].copy_from_slice(&data[..len]);
+}
+
+pub fn new_mapper(rom: NesRom) -> Box<dyn Mapper> {
+    match rom.mapper {
+        0 => Box::new(Nrom::new(rom)),
+        1 => Box::new(Sxrom::new(rom)),
+        2 => Box::new(Uxrom::new(rom)),
+        3 => Box::new(Cnrom::new(rom)),
+        other => panic!("unsupported mapper: {}", other),
+    }
+}
+
+//https://www.nesdev.org/wiki/NROM
+struct Nrom {
+    prg_rom: Vec<u8>,
+    chr_rom: Vec<u8>,
+    chr_ram: bool,
+    prg_ram: Vec<u8>,
+    mirroring: Mirroring,
+}
+
+impl Nrom {
+    fn new(rom: NesRom) -> Self {
+        let NesRom { prg_rom, chr_rom, chr_ram, prg_ram, mirror, .. } = rom;
+        Nrom {
+            prg_rom,
+            chr_rom,
+            chr_ram,
+            prg_ram,
+            mirroring: mirror,
+        }
+    }
+
+    // NROM-128 mirrors its single 16KB bank across $8000-$BFFF and $C000-$FFFF.
+    fn prg_addr(&self, addr: u16) -> usize {
+        let offset = (addr - 0x8000) as usize;
+        if self.prg_rom.len() <= 0x4000 {
+            offset % 0x4000
+        } else {
+            offset
+        }
+    }
+}
+
+impl Mapper for Nrom {
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize],
+            _ => self.prg_rom[self.prg_addr(addr)],
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, val: u8) {
+        if let 0x6000..=0x7FFF = addr {
+            self.prg_ram[(addr - 0x6000) as usize] = val;
+        }
+        // PRG-ROM is not writable on NROM
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.chr_rom[addr as usize]
+    }
+
+    fn ppu_write(&mut self, addr: u16, val: u8) {
+        if self.chr_ram {
+            self.chr_rom[addr as usize] = val;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn load_prg_ram(&mut self, data: &[u8]) {
+        load_prg_ram_into(&mut self.prg_ram, data);
+    }
+}
+
+//https://www.nesdev.org/wiki/UxROM
+struct Uxrom {
+    prg_rom: Vec<u8>,
+    chr_rom: Vec<u8>,
+    chr_ram: bool,
+    prg_ram: Vec<u8>,
+    mirroring: Mirroring,
+    prg_bank: usize,
+}
+
+impl Uxrom {
+    fn new(rom: NesRom) -> Self {
+        let NesRom { prg_rom, chr_rom, chr_ram, prg_ram, mirror, .. } = rom;
+        Uxrom {
+            prg_rom,
+            chr_rom,
+            chr_ram,
+            prg_ram,
+            mirroring: mirror,
+            prg_bank: 0,
+        }
+    }
+
+    fn prg_bank_count(&self) -> usize {
+        self.prg_rom.len() / 0x4000
+    }
+}
+
+impl Mapper for Uxrom {
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize],
+            0x8000..=0xBFFF => {
+                let base = self.prg_bank * 0x4000;
+                self.prg_rom[base + (addr - 0x8000) as usize]
+            }
+            0xC000..=0xFFFF => {
+                // Last bank is fixed at $C000.
+                let base = (self.prg_bank_count() - 1) * 0x4000;
+                self.prg_rom[base + (addr - 0xC000) as usize]
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize] = val,
+            0x8000..=0xFFFF => self.prg_bank = (val & 0x0F) as usize % self.prg_bank_count(),
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.chr_rom[addr as usize]
+    }
+
+    fn ppu_write(&mut self, addr: u16, val: u8) {
+        if self.chr_ram {
+            self.chr_rom[addr as usize] = val;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn load_prg_ram(&mut self, data: &[u8]) {
+        load_prg_ram_into(&mut self.prg_ram, data);
+    }
+}
+
+//https://www.nesdev.org/wiki/CNROM
+struct Cnrom {
+    prg_rom: Vec<u8>,
+    chr_rom: Vec<u8>,
+    chr_ram: bool,
+    prg_ram: Vec<u8>,
+    mirroring: Mirroring,
+    chr_bank: usize,
+}
+
+impl Cnrom {
+    fn new(rom: NesRom) -> Self {
+        let NesRom { prg_rom, chr_rom, chr_ram, prg_ram, mirror, .. } = rom;
+        Cnrom {
+            prg_rom,
+            chr_rom,
+            chr_ram,
+            prg_ram,
+            mirroring: mirror,
+            chr_bank: 0,
+        }
+    }
+
+    fn prg_addr(&self, addr: u16) -> usize {
+        let offset = (addr - 0x8000) as usize;
+        if self.prg_rom.len() <= 0x4000 {
+            offset % 0x4000
+        } else {
+            offset
+        }
+    }
+
+    fn chr_bank_count(&self) -> usize {
+        (self.chr_rom.len() / 0x2000).max(1)
+    }
+}
+
+impl Mapper for Cnrom {
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize],
+            _ => self.prg_rom[self.prg_addr(addr)],
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, val: u8) {
+        if let 0x6000..=0x7FFF = addr {
+            self.prg_ram[(addr - 0x6000) as usize] = val;
+            return;
+        }
+        self.chr_bank = val as usize % self.chr_bank_count();
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.chr_rom[self.chr_bank * 0x2000 + addr as usize]
+    }
+
+    fn ppu_write(&mut self, addr: u16, val: u8) {
+        if self.chr_ram {
+            let bank = self.chr_bank;
+            self.chr_rom[bank * 0x2000 + addr as usize] = val;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn load_prg_ram(&mut self, data: &[u8]) {
+        load_prg_ram_into(&mut self.prg_ram, data);
+    }
+}
+
+//https://www.nesdev.org/wiki/MMC1
+struct Sxrom {
+    prg_rom: Vec<u8>,
+    chr_rom: Vec<u8>,
+    chr_ram: bool,
+    prg_ram: Vec<u8>,
+
+    shift: u8,
+    shift_count: u8,
+
+    control: u8,
+    chr_bank_0: u8,
+    chr_bank_1: u8,
+    prg_bank: u8,
+
+    mirroring: Mirroring,
+}
+
+impl Sxrom {
+    fn new(rom: NesRom) -> Self {
+        let NesRom { prg_rom, chr_rom, chr_ram, prg_ram, mirror, .. } = rom;
+        Sxrom {
+            prg_rom,
+            chr_rom,
+            chr_ram,
+            prg_ram,
+            shift: 0,
+            shift_count: 0,
+            control: 0x0C, // power-on state: PRG mode 3 (fix last bank at $C000)
+            chr_bank_0: 0,
+            chr_bank_1: 0,
+            prg_bank: 0,
+            mirroring: mirror,
+        }
+    }
+
+    fn prg_bank_count(&self) -> usize {
+        self.prg_rom.len() / 0x4000
+    }
+
+    fn chr_bank_count(&self) -> usize {
+        (self.chr_rom.len() / 0x1000).max(1)
+    }
+
+    fn prg_mode(&self) -> u8 {
+        (self.control >> 2) & 0b11
+    }
+
+    fn chr_mode(&self) -> u8 {
+        (self.control >> 4) & 0b1
+    }
+
+    fn write_control(&mut self, val: u8) {
+        self.control = val;
+        self.mirroring = match val & 0b11 {
+            0 => Mirroring::SingleScreenLower,
+            1 => Mirroring::SingleScreenUpper,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        };
+    }
+
+    fn load_register(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x8000..=0x9FFF => self.write_control(val),
+            0xA000..=0xBFFF => self.chr_bank_0 = val,
+            0xC000..=0xDFFF => self.chr_bank_1 = val,
+            0xE000..=0xFFFF => self.prg_bank = val & 0x0F,
+            _ => {}
+        }
+    }
+}
+
+impl Mapper for Sxrom {
+    fn cpu_read(&mut self, addr: u16) -> u8 {
+        match addr {
+            0x6000..=0x7FFF => self.prg_ram[(addr - 0x6000) as usize],
+            0x8000..=0xFFFF => {
+                let bank = self.prg_bank as usize;
+                let last_bank = self.prg_bank_count() - 1;
+                let (bank_lo, bank_hi) = match self.prg_mode() {
+                    0 | 1 => {
+                        // 32KB mode: ignore the low bit of the bank register.
+                        let bank32 = bank & !1;
+                        (bank32, bank32 + 1)
+                    }
+                    2 => (0, bank),
+                    _ => (bank, last_bank),
+                };
+                let bank_count = self.prg_bank_count();
+                if addr < 0xC000 {
+                    self.prg_rom[(bank_lo % bank_count) * 0x4000 + (addr - 0x8000) as usize]
+                } else {
+                    self.prg_rom[(bank_hi % bank_count) * 0x4000 + (addr - 0xC000) as usize]
+                }
+            }
+            _ => 0,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, val: u8) {
+        if addr < 0x8000 {
+            if addr >= 0x6000 {
+                self.prg_ram[(addr - 0x6000) as usize] = val;
+            }
+            return;
+        }
+
+        if val & 0x80 != 0 {
+            // Reset bit: clear the shift register and force PRG mode 3.
+            self.shift = 0;
+            self.shift_count = 0;
+            self.control |= 0x0C;
+            return;
+        }
+
+        self.shift |= (val & 1) << self.shift_count;
+        self.shift_count += 1;
+        if self.shift_count == 5 {
+            let value = self.shift;
+            self.shift = 0;
+            self.shift_count = 0;
+            self.load_register(addr, value);
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        let bank_4k = if self.chr_mode() == 0 {
+            let bank_8k = (self.chr_bank_0 >> 1) as usize;
+            if addr < 0x1000 {
+                bank_8k * 2
+            } else {
+                bank_8k * 2 + 1
+            }
+        } else if addr < 0x1000 {
+            self.chr_bank_0 as usize
+        } else {
+            self.chr_bank_1 as usize
+        };
+        self.chr_rom[(bank_4k % self.chr_bank_count()) * 0x1000 + (addr as usize % 0x1000)]
+    }
+
+    fn ppu_write(&mut self, addr: u16, val: u8) {
+        if self.chr_ram {
+            let bank_4k = if self.chr_mode() == 0 {
+                let bank_8k = (self.chr_bank_0 >> 1) as usize;
+                if addr < 0x1000 {
+                    bank_8k * 2
+                } else {
+                    bank_8k * 2 + 1
+                }
+            } else if addr < 0x1000 {
+                self.chr_bank_0 as usize
+            } else {
+                self.chr_bank_1 as usize
+            };
+            let count = self.chr_bank_count();
+            self.chr_rom[(bank_4k % count) * 0x1000 + (addr as usize % 0x1000)] = val;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn load_prg_ram(&mut self, data: &[u8]) {
+        load_prg_ram_into(&mut self.prg_ram, data);
+    }
+}
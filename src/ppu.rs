@@ -1,16 +1,20 @@
 use crate::cartridge;
+use crate::mapper::Mapper;
 use bitflags::bitflags;
 
 //https://www.nesdev.org/wiki/PPU_registers
 pub struct PPU {
     internal_data_buf: u8,
 
-    // Charater ROM and mirroring from catridge
-    pub chr_rom: Vec<u8>,
-    pub mirroring: cartridge::Mirroring,
+    // CHR access and nametable mirroring are delegated to the cartridge mapper,
+    // since both can change at runtime (CHR bank switching, MMC1 mirroring).
+    pub mapper: Box<dyn Mapper>,
     //Palaette tables
     pub palette_table: [u8; 32],
-    pub vram: [u8; 2048],
+    // 4KB of physical name-table RAM: one 1KB bank per logical name table.
+    // Two-screen cartridges only ever address two of these banks (aliased per
+    // `mirror_vram_addr`); four-screen cartridges use all four.
+    pub vram: [u8; 4096],
     pub oam_data: [u8; 256],
 
     palette_ram: [u8; 32],
@@ -26,10 +30,21 @@ pub struct PPU {
     pub addr: AddrRegister,
     pub data: u8,
     pub oam_dma: u8,
+
+    // Position within the current 341-dot x 262-scanline NTSC frame.
+    cycle: usize,
+    scanline: usize,
 }
 
+pub const FRAME_WIDTH: usize = 256;
+pub const FRAME_HEIGHT: usize = 240;
+
+const STATUS_SPRITE_OVERFLOW: u8 = 0b0010_0000;
+const STATUS_SPRITE_ZERO_HIT: u8 = 0b0100_0000;
+const STATUS_VBLANK: u8 = 0b1000_0000;
+
 impl PPU {
-    pub fn new(chr_rom: Vec<u8>, mirroring: cartridge::Mirroring) -> PPU {
+    pub fn new(mapper: Box<dyn Mapper>) -> PPU {
         PPU {
             internal_data_buf: 0,
             palette_ram: [0; 32],
@@ -44,13 +59,44 @@ impl PPU {
             addr: AddrRegister::new(),
             data: 0,
             oam_dma: 0,
-            chr_rom: chr_rom,
+            mapper,
             palette_table: [0; 32],
-            vram: [0; 2048],
-            mirroring: mirroring,
+            vram: [0; 4096],
+            cycle: 0,
+            scanline: 0,
         }
     }
 
+    // Advances the PPU by `cycles` dots, returning `true` when an NMI should
+    // fire (VBLANK just started and `GENERATE_NMI` is set).
+    pub fn tick(&mut self, cycles: usize) -> bool {
+        let mut nmi_triggered = false;
+
+        for _ in 0..cycles {
+            self.cycle += 1;
+            if self.cycle < 341 {
+                continue;
+            }
+            self.cycle = 0;
+            self.scanline += 1;
+
+            if self.scanline == 241 {
+                self.status |= STATUS_VBLANK;
+                if self.ctrl.contains(ControlRegister::GENERATE_NMI) {
+                    nmi_triggered = true;
+                }
+            } else if self.scanline == 261 {
+                // Pre-render scanline: clear VBLANK and the sprite flags ahead
+                // of the next frame.
+                self.status &= !(STATUS_VBLANK | STATUS_SPRITE_ZERO_HIT | STATUS_SPRITE_OVERFLOW);
+            } else if self.scanline > 261 {
+                self.scanline = 0;
+            }
+        }
+
+        nmi_triggered
+    }
+
     fn write_to_ppu_addr(&mut self, data: u8) {
         self.addr.update(data);
     }
@@ -64,19 +110,24 @@ impl PPU {
     }
 
     // https://wiki.nesdev.org/w/index.php/Mirroring
+    //
+    // Mirroring is consulted from the mapper, not a fixed value captured at
+    // cartridge load, since mappers like MMC1 can switch it at runtime.
     pub fn mirror_vram_addr(&self, addr: u16) -> u16 {
         let mirrored_vram = addr & 0b10111111111111; // mirror down 0x3000-0x3eff to 0x2000 - 0x2eff
-        let vram_index = mirrored_vram - 0x2000; // to vram vector
-        let name_table = vram_index / 0x400; // to the name table index
-        match (&self.mirroring, name_table) {
-            (cartridge::Mirroring::Vertical, 2) | (cartridge::Mirroring::Vertical, 3) => {
-                vram_index - 0x800
-            }
-            (cartridge::Mirroring::Horizontal, 2) => vram_index - 0x400,
-            (cartridge::Mirroring::Horizontal, 1) => vram_index - 0x400,
-            (cartridge::Mirroring::Horizontal, 3) => vram_index - 0x800,
-            _ => vram_index,
-        }
+        let vram_index = mirrored_vram - 0x2000; // offset into the 4 logical 1KB name tables
+        let name_table = vram_index / 0x400; // logical name-table index: 0-3
+        let offset = vram_index % 0x400;
+        let physical_bank = match self.mapper.mirroring() {
+            cartridge::Mirroring::Vertical => name_table % 2,
+            cartridge::Mirroring::Horizontal => name_table / 2,
+            // Four-screen cartridges provide distinct RAM for all four
+            // name tables, so each keeps its own physical bank.
+            cartridge::Mirroring::FourScreen => name_table,
+            cartridge::Mirroring::SingleScreenLower => 0,
+            cartridge::Mirroring::SingleScreenUpper => 1,
+        };
+        physical_bank * 0x400 + offset
     }
 
     fn read_data(&mut self) -> u8 {
@@ -86,7 +137,7 @@ impl PPU {
         match addr {
             0..=0x1fff => {
                 let result = self.internal_data_buf;
-                self.internal_data_buf = self.chr_rom[addr as usize];
+                self.internal_data_buf = self.mapper.ppu_read(addr);
                 result
             }
             0x2000..=0x2fff => {
@@ -98,10 +149,331 @@ impl PPU {
                 "addr space 0x3000..0x3eff is not expected to be used, requested = {} ",
                 addr
             ),
-            //0x3f00..=0x3fff => self.palette_table[(addr - 0x3f00) as usize],
+            0x3f00..=0x3fff => self.palette_table[mirror_palette_addr(addr)],
+            _ => panic!("unexpected access to mirrored space {}", addr),
+        }
+    }
+
+    fn write_data(&mut self, val: u8) {
+        let addr = self.addr.get();
+        self.increment_vram_addr();
+
+        match addr {
+            0..=0x1fff => self.mapper.ppu_write(addr, val),
+            0x2000..=0x2fff => self.vram[self.mirror_vram_addr(addr) as usize] = val,
+            0x3000..=0x3eff => panic!(
+                "addr space 0x3000..0x3eff is not expected to be used, requested = {} ",
+                addr
+            ),
+            0x3f00..=0x3fff => self.palette_table[mirror_palette_addr(addr)] = val,
             _ => panic!("unexpected access to mirrored space {}", addr),
         }
     }
+
+    //https://www.nesdev.org/wiki/PPU_registers
+    // CPU-visible registers at $2000-$2007, mirrored every 8 bytes through $3FFF.
+    pub fn read_register(&mut self, addr: u16) -> u8 {
+        match mirror_register_addr(addr) {
+            0x2002 => {
+                let result = self.status;
+                self.status &= !STATUS_VBLANK;
+                self.addr.reset_latch();
+                result
+            }
+            0x2004 => self.oam_data[self.oam_addr as usize],
+            0x2007 => self.read_data(),
+            // PPUCTRL/PPUMASK/OAMADDR/PPUSCROLL/PPUADDR are write-only.
+            _ => 0,
+        }
+    }
+
+    pub fn write_register(&mut self, addr: u16, val: u8) {
+        match mirror_register_addr(addr) {
+            0x2000 => self.write_to_ctrl(val),
+            0x2001 => self.mask = val,
+            0x2003 => self.oam_addr = val,
+            0x2004 => {
+                self.oam_data[self.oam_addr as usize] = val;
+                self.oam_addr = self.oam_addr.wrapping_add(1);
+            }
+            0x2005 => self.scroll = val,
+            0x2006 => self.write_to_ppu_addr(val),
+            0x2007 => self.write_data(val),
+            // PPUSTATUS is read-only.
+            _ => {}
+        }
+    }
+
+    //https://www.nesdev.org/wiki/PPU_registers/OAM#OAM_DMA_($4014)_%3E_write
+    // The CPU bus exposes this at $4014: a full 256-byte CPU page is copied
+    // into OAM starting at the current `oam_addr`.
+    pub fn oam_dma(&mut self, page: [u8; 256]) {
+        for byte in page.iter() {
+            self.oam_data[self.oam_addr as usize] = *byte;
+            self.oam_addr = self.oam_addr.wrapping_add(1);
+        }
+    }
+
+    fn bg_pattern_table_addr(&self) -> u16 {
+        if self.ctrl.contains(ControlRegister::BACKROUND_PATTERN_ADDR) {
+            0x1000
+        } else {
+            0x0000
+        }
+    }
+
+    fn sprite_pattern_table_addr(&self) -> u16 {
+        if self.ctrl.contains(ControlRegister::SPRITE_PATTERN_ADDR) {
+            0x1000
+        } else {
+            0x0000
+        }
+    }
+
+    fn sprite_height(&self) -> usize {
+        if self.ctrl.contains(ControlRegister::SPRITE_SIZE) {
+            16
+        } else {
+            8
+        }
+    }
+
+    // Background palette (4 NES color indices) for the tile at `tile_column`,
+    // `tile_row`, read from the nametable's trailing attribute table.
+    fn bg_palette(&self, tile_column: usize, tile_row: usize) -> [u8; 4] {
+        let attr_table_idx = (tile_row / 4) * 8 + tile_column / 4;
+        let attr_addr = 0x23c0 + attr_table_idx as u16;
+        let attr_byte = self.vram[self.mirror_vram_addr(attr_addr) as usize];
+
+        let palette_idx = match (tile_column % 4 / 2, tile_row % 4 / 2) {
+            (0, 0) => attr_byte & 0b11,
+            (1, 0) => (attr_byte >> 2) & 0b11,
+            (0, 1) => (attr_byte >> 4) & 0b11,
+            (1, 1) => (attr_byte >> 6) & 0b11,
+            _ => unreachable!(),
+        };
+
+        let start = 1 + palette_idx as usize * 4;
+        [
+            self.palette_table[0],
+            self.palette_table[start],
+            self.palette_table[start + 1],
+            self.palette_table[start + 2],
+        ]
+    }
+
+    fn sprite_palette(&self, palette_idx: u8) -> [u8; 4] {
+        let start = 0x11 + palette_idx as usize * 4;
+        [
+            0,
+            self.palette_table[start],
+            self.palette_table[start + 1],
+            self.palette_table[start + 2],
+        ]
+    }
+
+    fn set_pixel(frame: &mut [u8; FRAME_WIDTH * FRAME_HEIGHT * 3], x: usize, y: usize, rgb: (u8, u8, u8)) {
+        if x >= FRAME_WIDTH || y >= FRAME_HEIGHT {
+            return;
+        }
+        let idx = (y * FRAME_WIDTH + x) * 3;
+        frame[idx] = rgb.0;
+        frame[idx + 1] = rgb.1;
+        frame[idx + 2] = rgb.2;
+    }
+
+    /// Composes the current nametable + OAM state into one RGB frame.
+    // &mut self rather than &self: rendering sets the sprite-0-hit and
+    // sprite-overflow status flags as a side effect of sprite evaluation.
+    pub fn render_frame(&mut self) -> [u8; FRAME_WIDTH * FRAME_HEIGHT * 3] {
+        let mut frame = [0u8; FRAME_WIDTH * FRAME_HEIGHT * 3];
+        let mut bg_opaque = [false; FRAME_WIDTH * FRAME_HEIGHT];
+
+        self.render_background(&mut frame, &mut bg_opaque);
+        self.render_sprites(&mut frame, &bg_opaque);
+
+        frame
+    }
+
+    fn render_background(
+        &mut self,
+        frame: &mut [u8; FRAME_WIDTH * FRAME_HEIGHT * 3],
+        bg_opaque: &mut [bool; FRAME_WIDTH * FRAME_HEIGHT],
+    ) {
+        let bank = self.bg_pattern_table_addr();
+
+        for tile_row in 0..30 {
+            for tile_column in 0..32 {
+                let nametable_addr = 0x2000 + (tile_row * 32 + tile_column) as u16;
+                let tile_idx = self.vram[self.mirror_vram_addr(nametable_addr) as usize] as u16;
+                let palette = self.bg_palette(tile_column, tile_row);
+
+                let mut tile = [0u8; 16];
+                for (offset, byte) in tile.iter_mut().enumerate() {
+                    *byte = self.mapper.ppu_read(bank + tile_idx * 16 + offset as u16);
+                }
+
+                for y in 0..8 {
+                    let mut upper = tile[y];
+                    let mut lower = tile[y + 8];
+                    for x in (0..8).rev() {
+                        let value = (lower & 1) << 1 | (upper & 1);
+                        upper >>= 1;
+                        lower >>= 1;
+
+                        let color_idx = match value {
+                            0 => self.palette_table[0],
+                            1 => palette[1],
+                            2 => palette[2],
+                            _ => palette[3],
+                        };
+
+                        let px = tile_column * 8 + x;
+                        let py = tile_row * 8 + y;
+                        bg_opaque[py * FRAME_WIDTH + px] = value != 0;
+                        Self::set_pixel(frame, px, py, SYSTEM_PALETTE[color_idx as usize]);
+                    }
+                }
+            }
+        }
+    }
+
+    // Mirrors real hardware's per-scanline sprite evaluation: scans `oam_data`
+    // for sprites visible on `scanline`, copies up to 8 into
+    // `secondary_sprite_list_ram`, and sets the overflow flag beyond that.
+    // Returns the number of sprites found and which of them is OAM sprite 0.
+    fn evaluate_sprites(&mut self, scanline: usize) -> (usize, [bool; 8]) {
+        let sprite_height = self.sprite_height();
+        let mut count = 0;
+        let mut is_sprite_zero = [false; 8];
+
+        for i in (0..self.oam_data.len()).step_by(4) {
+            let sprite_y = self.oam_data[i] as usize;
+            if scanline < sprite_y || scanline >= sprite_y + sprite_height {
+                continue;
+            }
+            if count == 8 {
+                self.status |= STATUS_SPRITE_OVERFLOW;
+                break;
+            }
+            self.secondary_sprite_list_ram[count * 4..count * 4 + 4]
+                .copy_from_slice(&self.oam_data[i..i + 4]);
+            is_sprite_zero[count] = i == 0;
+            count += 1;
+        }
+
+        (count, is_sprite_zero)
+    }
+
+    fn render_sprites(
+        &mut self,
+        frame: &mut [u8; FRAME_WIDTH * FRAME_HEIGHT * 3],
+        bg_opaque: &[bool; FRAME_WIDTH * FRAME_HEIGHT],
+    ) {
+        let sprite_height = self.sprite_height();
+        let sprite_bank = self.sprite_pattern_table_addr();
+
+        for scanline in 0..FRAME_HEIGHT {
+            let (count, is_sprite_zero) = self.evaluate_sprites(scanline);
+
+            // Draw highest-priority (lowest OAM index) sprites last so they
+            // end up on top.
+            for slot in (0..count).rev() {
+                let base = slot * 4;
+                let sprite_y = self.secondary_sprite_list_ram[base] as usize;
+                let tile_idx = self.secondary_sprite_list_ram[base + 1] as u16;
+                let attributes = self.secondary_sprite_list_ram[base + 2];
+                let sprite_x = self.secondary_sprite_list_ram[base + 3] as usize;
+
+                let flip_vertical = attributes & 0b1000_0000 != 0;
+                let flip_horizontal = attributes & 0b0100_0000 != 0;
+                let behind_background = attributes & 0b0010_0000 != 0;
+                let palette = self.sprite_palette(attributes & 0b11);
+
+                let row = scanline - sprite_y;
+                let pattern_row = if flip_vertical { sprite_height - 1 - row } else { row };
+
+                // In 8x16 mode the tile index's low bit selects the pattern
+                // table and the tile pair (even, odd) is always used together.
+                let (bank, tile) = if sprite_height == 16 {
+                    (
+                        if tile_idx & 1 == 0 { 0x0000 } else { 0x1000 },
+                        (tile_idx & !1) + (pattern_row / 8) as u16,
+                    )
+                } else {
+                    (sprite_bank, tile_idx)
+                };
+
+                let addr = bank + tile * 16 + (pattern_row % 8) as u16;
+                let mut upper = self.mapper.ppu_read(addr);
+                let mut lower = self.mapper.ppu_read(addr + 8);
+
+                for col in (0..8).rev() {
+                    let value = (lower & 1) << 1 | (upper & 1);
+                    upper >>= 1;
+                    lower >>= 1;
+                    if value == 0 {
+                        continue;
+                    }
+
+                    let x = if flip_horizontal { sprite_x + 7 - col } else { sprite_x + col };
+                    if x >= FRAME_WIDTH {
+                        continue;
+                    }
+                    let opaque_bg = bg_opaque[scanline * FRAME_WIDTH + x];
+
+                    if is_sprite_zero[slot] && opaque_bg {
+                        self.status |= STATUS_SPRITE_ZERO_HIT;
+                    }
+
+                    if behind_background && opaque_bg {
+                        continue;
+                    }
+
+                    let color_idx = palette[value as usize];
+                    Self::set_pixel(frame, x, scanline, SYSTEM_PALETTE[color_idx as usize]);
+                }
+            }
+        }
+    }
+}
+
+// The NES's fixed 64-color output palette, indexed by the 2C02 PPU color index.
+#[rustfmt::skip]
+pub static SYSTEM_PALETTE: [(u8, u8, u8); 64] = [
+   (0x80, 0x80, 0x80), (0x00, 0x3D, 0xA6), (0x00, 0x12, 0xB0), (0x44, 0x00, 0x96),
+   (0xA1, 0x00, 0x5E), (0xC7, 0x00, 0x28), (0xBA, 0x06, 0x00), (0x8C, 0x17, 0x00),
+   (0x5C, 0x2F, 0x00), (0x10, 0x45, 0x00), (0x05, 0x4A, 0x00), (0x00, 0x47, 0x2E),
+   (0x00, 0x41, 0x66), (0x00, 0x00, 0x00), (0x05, 0x05, 0x05), (0x05, 0x05, 0x05),
+   (0xC7, 0xC7, 0xC7), (0x00, 0x77, 0xFF), (0x21, 0x55, 0xFF), (0x82, 0x37, 0xFA),
+   (0xEB, 0x2F, 0xB5), (0xFF, 0x29, 0x50), (0xFF, 0x22, 0x00), (0xD6, 0x32, 0x00),
+   (0xC4, 0x62, 0x00), (0x35, 0x80, 0x00), (0x05, 0x8F, 0x00), (0x00, 0x8A, 0x55),
+   (0x00, 0x99, 0xCC), (0x21, 0x21, 0x21), (0x09, 0x09, 0x09), (0x09, 0x09, 0x09),
+   (0xFF, 0xFF, 0xFF), (0x0F, 0xD7, 0xFF), (0x69, 0xA2, 0xFF), (0xD4, 0x80, 0xFF),
+   (0xFF, 0x45, 0xF3), (0xFF, 0x61, 0x8B), (0xFF, 0x88, 0x33), (0xFF, 0x9C, 0x12),
+   (0xFA, 0xBC, 0x20), (0x9F, 0xE3, 0x0E), (0x2B, 0xF0, 0x35), (0x0C, 0xF0, 0xA4),
+   (0x05, 0xFB, 0xFF), (0x5E, 0x5E, 0x5E), (0x0D, 0x0D, 0x0D), (0x0D, 0x0D, 0x0D),
+   (0xFF, 0xFF, 0xFF), (0xA6, 0xFC, 0xFF), (0xB3, 0xEC, 0xFF), (0xDA, 0xAB, 0xEB),
+   (0xFF, 0xA8, 0xF9), (0xFF, 0xAB, 0xB3), (0xFF, 0xD2, 0xB0), (0xFF, 0xEF, 0xA6),
+   (0xFF, 0xF7, 0x9C), (0xD7, 0xE8, 0x95), (0xA6, 0xED, 0xAF), (0xA2, 0xF2, 0xDA),
+   (0x99, 0xFF, 0xFC), (0xDD, 0xDD, 0xDD), (0x11, 0x11, 0x11), (0x11, 0x11, 0x11),
+];
+
+// $2000-$3FFF mirrors the 8 PPU registers every 8 bytes.
+fn mirror_register_addr(addr: u16) -> u16 {
+    addr & 0b0010_0000_0000_0111
+}
+
+// https://www.nesdev.org/wiki/PPU_palettes
+// $3F00-$3FFF mirrors the 32-byte palette RAM every 32 bytes, and the
+// sprite backdrop entries at $3F10/$14/$18/$1C additionally mirror their
+// background counterparts at $3F00/$04/$08/$0C.
+fn mirror_palette_addr(addr: u16) -> usize {
+    let mirrored = (addr - 0x3f00) % 32;
+    match mirrored {
+        0x10 | 0x14 | 0x18 | 0x1c => (mirrored - 0x10) as usize,
+        _ => mirrored as usize,
+    }
 }
 
 //https://www.nesdev.org/wiki/PPU_registers#PPUADDR_-_VRAM_address_($2006_write)
@@ -1,36 +1,98 @@
 use crate::cartridge::NesRom;
+use crate::host::{Buttons, HostPlatform};
+use crate::mapper;
+use crate::ppu::PPU;
 use mos6502::cpu;
 use std::fs;
+use std::path::PathBuf;
+
+// One NTSC frame: 341 PPU dots per scanline x 262 scanlines.
+const FRAME_PPU_CYCLES: usize = 341 * 262;
 
 pub struct Emulator {
     pub cpu_state: cpu::CPU,
     pub nes_rom: NesRom,
+    // CPU-facing $2000-$2007 register dispatch and $4014 OAM DMA route
+    // through here; see `PPU::read_register`/`write_register`/`oam_dma`.
+    pub ppu: PPU,
+    // On-disk save file for battery-backed PRG-RAM, derived from the ROM's
+    // file path; `None` when constructed from raw bytes (`new_with_gamecodes`)
+    // since there is no ROM path to derive one from.
+    pub save_path: Option<PathBuf>,
+    // Controller state from the most recent `run_frame` poll.
+    pub buttons: Buttons,
 }
 
 impl Emulator {
-    pub fn new_with_gamecodes(game_codes: Vec<u8>) -> Emulator {
-        let nes_rom_result = NesRom::new(&game_codes);
-        let nes_rom = match nes_rom_result {
-            Ok(rom_bytes) => rom_bytes,
-            Err(error) => panic!("Failed to load nes game code with {:?}", error),
-        };
-        Emulator {
+    // Host-platform-agnostic constructor: takes ROM bytes directly so WASM
+    // and no_std embedded frontends can supply them without `std::fs`.
+    pub fn new_with_gamecodes(game_codes: Vec<u8>) -> Result<Emulator, String> {
+        let nes_rom = NesRom::new(&game_codes)?;
+        let ppu = PPU::new(mapper::new_mapper(nes_rom.clone()));
+        Ok(Emulator {
             cpu_state: cpu::CPU::new(),
             nes_rom: nes_rom,
-        }
+            ppu: ppu,
+            save_path: None,
+            buttons: Buttons::empty(),
+        })
     }
 
     pub fn new(&mut self, file_path: String) -> Emulator {
-        let rom_contents = self.load_rom(file_path);
+        let rom_contents = self.load_rom(file_path.clone());
         let nes_rom = NesRom::new(&rom_contents).unwrap();
-        Emulator {
+        let ppu = PPU::new(mapper::new_mapper(nes_rom.clone()));
+        let mut emulator = Emulator {
             cpu_state: cpu::CPU::new(),
             nes_rom: nes_rom,
-        }
+            ppu: ppu,
+            save_path: Some(PathBuf::from(file_path).with_extension("sav")),
+            buttons: Buttons::empty(),
+        };
+        emulator.load_save();
+        emulator
     }
 
     fn load_rom(&mut self, file_path: String) -> Vec<u8> {
         let contents = fs::read(file_path).expect("Should be able to read file and content");
         contents
     }
+
+    // Drives one frame: ticks the PPU for a full NTSC frame's worth of
+    // cycles and renders the result, and pushes/pulls through `host` instead
+    // of touching a window, file system, or audio backend directly. The CPU
+    // isn't wired to a cycle-stepping bus yet, so there's no NMI delivery or
+    // audio synthesis; `host.queue_audio` is called with an empty buffer
+    // until the APU lands.
+    pub fn run_frame(&mut self, host: &mut dyn HostPlatform) {
+        self.ppu.tick(FRAME_PPU_CYCLES);
+        let frame = self.ppu.render_frame();
+        host.render(&frame);
+        self.buttons = host.poll_input();
+        host.queue_audio(&[]);
+    }
+
+    // Reads an existing `.sav` file next to the ROM into PRG-RAM, if the
+    // cartridge is battery-backed and a save exists.
+    fn load_save(&mut self) {
+        if !self.nes_rom.battery {
+            return;
+        }
+        if let Some(path) = &self.save_path {
+            if let Ok(save_data) = fs::read(path) {
+                self.ppu.mapper.load_prg_ram(&save_data);
+            }
+        }
+    }
+
+    // Writes PRG-RAM out to the `.sav` file next to the ROM, for
+    // battery-backed cartridges.
+    pub fn save(&self) {
+        if !self.nes_rom.battery {
+            return;
+        }
+        if let Some(path) = &self.save_path {
+            fs::write(path, self.ppu.mapper.prg_ram()).expect("Should be able to write save file");
+        }
+    }
 }